@@ -0,0 +1,178 @@
+//! Iterates vertical files across a whole corpus directory tree, chaining
+//! them so callers see a single continuous stream of lines.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{CorpyResult, Line, Vertical};
+
+pub struct Corpus {
+    files: Vec<PathBuf>,
+    index: usize,
+    current: Option<Vertical>,
+}
+
+impl Corpus {
+    /// Walks `root`, collecting files whose name ends in one of `exts`
+    /// (e.g. `"vert"`, `"vert.gz"`) in deterministic sorted order, and
+    /// chains their lines into a single stream.
+    pub fn new(root: &str, exts: &[&str]) -> CorpyResult<Self> {
+        let mut files = Vec::new();
+        collect_files(Path::new(root), exts, &mut files).map_err(|e| e.to_string())?;
+        files.sort();
+        Ok(Corpus {
+            files,
+            index: 0,
+            current: None,
+        })
+    }
+
+    /// The file the most recently yielded line came from, if any.
+    pub fn current_file(&self) -> Option<&Path> {
+        if self.index == 0 {
+            None
+        } else {
+            self.files.get(self.index - 1).map(PathBuf::as_path)
+        }
+    }
+
+    pub fn next_line(&mut self) -> Option<CorpyResult<String>> {
+        self.advance(Vertical::next_line)
+    }
+
+    /// Like [`Corpus::next_line`], but yields parsed [`Line`]s, rolling
+    /// over from one file to the next exactly like `next_line` does.
+    pub fn next_parsed(&mut self) -> Option<CorpyResult<Line>> {
+        self.advance(Vertical::next_parsed)
+    }
+
+    /// Pulls the next item from the current file via `f`, opening
+    /// subsequent files in `self.files` as each one is exhausted.
+    fn advance<T>(
+        &mut self,
+        f: impl Fn(&mut Vertical) -> Option<CorpyResult<T>>,
+    ) -> Option<CorpyResult<T>> {
+        loop {
+            if let Some(vertical) = self.current.as_mut() {
+                if let Some(item) = f(vertical) {
+                    return Some(item);
+                }
+                self.current = None;
+            }
+
+            let path = self.files.get(self.index)?;
+            self.index += 1;
+            match Vertical::new(path.to_string_lossy().as_ref()) {
+                Ok(vertical) => self.current = Some(vertical),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+fn collect_files(dir: &Path, exts: &[&str], files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, exts, files)?;
+        } else if has_matching_ext(&path, exts) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn has_matching_ext(path: &Path, exts: &[&str]) -> bool {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+    exts.iter().any(|ext| name.ends_with(&format!(".{}", ext)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory under `std::env::temp_dir()`, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir_name = format!("corpy-test-{}-{}", name, std::process::id());
+            let dir = std::env::temp_dir().join(dir_name);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn chains_lines_across_files_in_sorted_order_and_descends_subdirs() {
+        let dir = TempDir::new("chains");
+        fs::write(dir.0.join("b.vert"), "b1\nb2\n").unwrap();
+        fs::write(dir.0.join("a.vert"), "a1\n").unwrap();
+        fs::create_dir_all(dir.0.join("sub")).unwrap();
+        fs::write(dir.0.join("sub").join("c.vert"), "c1\n").unwrap();
+        fs::write(dir.0.join("ignored.txt"), "nope\n").unwrap();
+
+        let mut corpus = Corpus::new(dir.0.to_str().unwrap(), &["vert"]).unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = corpus.next_line() {
+            lines.push(line.unwrap());
+        }
+
+        // a.vert < b.vert < sub/c.vert in sorted path order; ignored.txt excluded.
+        assert_eq!(lines, vec!["a1", "b1", "b2", "c1"]);
+    }
+
+    #[test]
+    fn current_file_tracks_the_file_the_last_line_came_from() {
+        let dir = TempDir::new("current-file");
+        fs::write(dir.0.join("a.vert"), "a1\n").unwrap();
+        fs::write(dir.0.join("b.vert"), "b1\n").unwrap();
+
+        let mut corpus = Corpus::new(dir.0.to_str().unwrap(), &["vert"]).unwrap();
+        assert!(corpus.current_file().is_none());
+
+        corpus.next_line().unwrap().unwrap();
+        assert_eq!(corpus.current_file().unwrap(), dir.0.join("a.vert"));
+
+        corpus.next_line().unwrap().unwrap();
+        assert_eq!(corpus.current_file().unwrap(), dir.0.join("b.vert"));
+
+        assert!(corpus.next_line().is_none());
+    }
+
+    #[test]
+    fn next_parsed_rolls_over_from_one_file_to_the_next() {
+        let dir = TempDir::new("next-parsed");
+        fs::write(dir.0.join("a.vert"), "a1\tx\n<s>\n").unwrap();
+        fs::write(dir.0.join("b.vert"), "</s>\nb1\ty\n").unwrap();
+
+        let mut corpus = Corpus::new(dir.0.to_str().unwrap(), &["vert"]).unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = corpus.next_parsed() {
+            lines.push(line.unwrap());
+        }
+
+        assert_eq!(
+            lines,
+            vec![
+                Line::Token(vec!["a1".into(), "x".into()]),
+                Line::StructOpen {
+                    name: "s".into(),
+                    attrs: vec![],
+                },
+                Line::StructClose { name: "s".into() },
+                Line::Token(vec!["b1".into(), "y".into()]),
+            ]
+        );
+    }
+}
@@ -1,13 +1,27 @@
 extern crate env_logger;
 #[macro_use]
 extern crate log;
+extern crate bzip2;
+extern crate flate2;
+extern crate zstd;
 
 // FFI
 extern crate libc;
 
+mod corpus;
+mod parse;
+mod writer;
+
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::{BufReader, Lines};
+use std::io::{self, BufReader, Lines};
+
+use bzip2::bufread::BzDecoder;
+use flate2::bufread::MultiGzDecoder;
+
+pub use corpus::Corpus;
+pub use parse::Line;
+pub use writer::VerticalWriter;
 
 // FFI
 use std::ptr;
@@ -16,20 +30,53 @@ use std::ffi::{CStr, CString};
 
 type CorpyResult<T> = Result<T, String>;
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: [u8; 3] = [b'B', b'Z', b'h'];
+
+/// Peeks at the first few bytes of `reader` and, based on magic numbers,
+/// wraps it in the appropriate streaming decompressor. Falls back to the
+/// raw reader when no known magic matches.
+fn decompressed<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn BufRead>> {
+    let header = reader.fill_buf()?;
+    if header.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else if header.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::stream::read::Decoder::new(
+            reader,
+        )?)))
+    } else if header.starts_with(&BZIP2_MAGIC) {
+        Ok(Box::new(BufReader::new(BzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
 pub struct Vertical {
-    lines: Lines<BufReader<File>>,
+    lines: Lines<Box<dyn BufRead>>,
+    /// An error hit mid-batch in `next_batch`, held back so the lines read
+    /// before it are returned first; surfaced on the following call.
+    pending_error: Option<String>,
 }
 
 impl Vertical {
     pub fn new(path: &str) -> CorpyResult<Self> {
         match File::open(path) {
-            Ok(file) => Ok(Vertical {
-                lines: BufReader::new(file).lines(),
-            }),
+            Ok(file) => {
+                let reader = decompressed(BufReader::new(file)).map_err(|e| e.to_string())?;
+                Ok(Vertical::from_reader(reader))
+            }
             Err(e) => Err(e.to_string()),
         }
     }
 
+    pub fn from_reader(reader: Box<dyn BufRead>) -> Self {
+        Vertical {
+            lines: reader.lines(),
+            pending_error: None,
+        }
+    }
+
     pub fn next_line(&mut self) -> Option<CorpyResult<String>> {
         match self.lines.next() {
             Some(value) => match value {
@@ -39,6 +86,54 @@ impl Vertical {
             None => None,
         }
     }
+
+    /// Pulls up to `n` lines at once, stopping early at EOF or the first
+    /// error. Lets callers amortize one FFI call over many lines instead of
+    /// paying the crossing cost per line. Lines read before a mid-batch
+    /// error are not discarded: the partial batch is returned, and the
+    /// error is surfaced on the following call instead.
+    pub fn next_batch(&mut self, n: usize) -> CorpyResult<LineBatch> {
+        if let Some(e) = self.pending_error.take() {
+            return Err(e);
+        }
+        // `n` comes straight from the FFI caller and is unvalidated, so it
+        // must not be trusted as an allocation size: an oversized or
+        // accidentally-huge `n` would otherwise abort the process.
+        let mut lines = Vec::new();
+        for _ in 0..n {
+            match self.next_line() {
+                Some(Ok(line)) => lines.push(CString::new(line).unwrap()),
+                Some(Err(e)) => {
+                    self.pending_error = Some(e);
+                    break;
+                }
+                None => break,
+            }
+        }
+        Ok(LineBatch { lines })
+    }
+
+    /// Like [`Vertical::next_line`], but tokenizes token lines on tabs and
+    /// parses structural tags instead of returning the raw `String`.
+    pub fn next_parsed(&mut self) -> Option<CorpyResult<Line>> {
+        self.next_line()
+            .map(|line| line.and_then(|line| parse::parse_line(&line)))
+    }
+}
+
+/// A batch of vertical lines pulled across the FFI boundary in one call.
+pub struct LineBatch {
+    lines: Vec<CString>,
+}
+
+impl LineBatch {
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&CString> {
+        self.lines.get(i)
+    }
 }
 
 #[no_mangle]
@@ -76,6 +171,13 @@ pub extern "C" fn vertical_new(path: *const c_char) -> *mut Vertical {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn vertical_from_stdin() -> *mut Vertical {
+    debug!("Allocating Vertical from stdin");
+    let stdin = Box::new(io::stdin().lock());
+    Box::into_raw(Box::new(Vertical::from_reader(stdin)))
+}
+
 #[no_mangle]
 pub extern "C" fn vertical_free(ptr: *mut Vertical) {
     if ptr.is_null() {
@@ -111,10 +213,548 @@ pub extern "C" fn vertical_next_line(ptr: *mut Vertical) -> *const c_char {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn vertical_next_batch(ptr: *mut Vertical, n: usize) -> *mut LineBatch {
+    let vertical = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    match vertical.next_batch(n) {
+        Ok(batch) => {
+            debug!("Allocating LineBatch of {} lines", batch.len());
+            Box::into_raw(Box::new(batch))
+        }
+        Err(e) => {
+            error!(
+                "Error in native code while reading next Vertical batch: {}",
+                e
+            );
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn line_batch_len(ptr: *const LineBatch) -> usize {
+    let batch = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    batch.len()
+}
+
+#[no_mangle]
+pub extern "C" fn line_batch_get(ptr: *const LineBatch, i: usize) -> *const c_char {
+    let batch = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    match batch.get(i) {
+        Some(line) => line.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn line_batch_free(ptr: *mut LineBatch) {
+    if ptr.is_null() {
+        return;
+    }
+    debug!("Deallocating LineBatch");
+    unsafe {
+        // Dropping the Box drops the Vec<CString>, which in turn drops and
+        // deallocates each owned CString exactly once.
+        Box::from_raw(ptr);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_next_parsed(ptr: *mut Vertical) -> *mut Line {
+    let vertical = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    match vertical.next_parsed() {
+        Some(Ok(line)) => {
+            debug!("Allocating parsed Line {:?}", line);
+            Box::into_raw(Box::new(line))
+        }
+        Some(Err(e)) => {
+            error!(
+                "Error in native code while parsing next Vertical line: {}",
+                e
+            );
+            ptr::null_mut()
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn line_free(ptr: *mut Line) {
+    if ptr.is_null() {
+        return;
+    }
+    debug!("Deallocating Line");
+    unsafe {
+        Box::from_raw(ptr);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn line_kind(ptr: *const Line) -> u8 {
+    let line = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    line.kind()
+}
+
+#[no_mangle]
+pub extern "C" fn line_column_count(ptr: *const Line) -> usize {
+    let line = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    line.column_count()
+}
+
+#[no_mangle]
+pub extern "C" fn line_column(ptr: *const Line, i: usize) -> *const c_char {
+    let line = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    match line.column(i) {
+        Some(col) => CString::new(col).unwrap().into_raw(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn line_struct_name(ptr: *const Line) -> *const c_char {
+    let line = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    match line.struct_name() {
+        Some(name) => CString::new(name).unwrap().into_raw(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn line_attr_count(ptr: *const Line) -> usize {
+    let line = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    line.attrs().len()
+}
+
+#[no_mangle]
+pub extern "C" fn line_attr_key(ptr: *const Line, i: usize) -> *const c_char {
+    let line = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    match line.attrs().get(i) {
+        Some((key, _)) => CString::new(key.as_str()).unwrap().into_raw(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn line_attr_value(ptr: *const Line, i: usize) -> *const c_char {
+    let line = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    match line.attrs().get(i) {
+        Some((_, value)) => CString::new(value.as_str()).unwrap().into_raw(),
+        None => ptr::null(),
+    }
+}
+
+unsafe fn cstr_array_to_vec<'a>(ptr: *const *const c_char, n: usize) -> Vec<&'a str> {
+    (0..n)
+        .map(|i| CStr::from_ptr(*ptr.add(i)).to_str().unwrap())
+        .collect()
+}
+
+unsafe fn cstr_pairs_to_vec<'a>(
+    keys: *const *const c_char,
+    values: *const *const c_char,
+    n: usize,
+) -> Vec<(&'a str, &'a str)> {
+    cstr_array_to_vec(keys, n)
+        .into_iter()
+        .zip(cstr_array_to_vec(values, n))
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_new(path: *const c_char) -> *mut VerticalWriter {
+    let path = unsafe {
+        assert!(!path.is_null());
+        CStr::from_ptr(path)
+    };
+    let path = path.to_str().unwrap();
+    match VerticalWriter::new(path) {
+        Ok(writer) => {
+            debug!("Allocating VerticalWriter {:?}", path);
+            Box::into_raw(Box::new(writer))
+        }
+        Err(e) => {
+            error!("Error in native code while creating {:?}: {}", path, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_stdout() -> *mut VerticalWriter {
+    debug!("Allocating VerticalWriter for stdout");
+    Box::into_raw(Box::new(VerticalWriter::stdout()))
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_free(ptr: *mut VerticalWriter) {
+    if ptr.is_null() {
+        return;
+    }
+    debug!("Deallocating VerticalWriter");
+    unsafe {
+        Box::from_raw(ptr);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_write_token(
+    ptr: *mut VerticalWriter,
+    columns: *const *const c_char,
+    n: usize,
+) -> bool {
+    let writer = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    let columns = unsafe { cstr_array_to_vec(columns, n) };
+    log_write_err(writer.write_token(&columns))
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_write_struct_open(
+    ptr: *mut VerticalWriter,
+    name: *const c_char,
+    keys: *const *const c_char,
+    values: *const *const c_char,
+    n: usize,
+) -> bool {
+    let writer = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
+    let attrs = unsafe { cstr_pairs_to_vec(keys, values, n) };
+    log_write_err(writer.write_struct_open(name, &attrs))
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_write_struct_close(
+    ptr: *mut VerticalWriter,
+    name: *const c_char,
+) -> bool {
+    let writer = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
+    log_write_err(writer.write_struct_close(name))
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_write_struct_empty(
+    ptr: *mut VerticalWriter,
+    name: *const c_char,
+    keys: *const *const c_char,
+    values: *const *const c_char,
+    n: usize,
+) -> bool {
+    let writer = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
+    let attrs = unsafe { cstr_pairs_to_vec(keys, values, n) };
+    log_write_err(writer.write_struct_empty(name, &attrs))
+}
+
+#[no_mangle]
+pub extern "C" fn vertical_writer_flush(ptr: *mut VerticalWriter) -> bool {
+    let writer = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    log_write_err(writer.flush())
+}
+
+fn log_write_err(result: CorpyResult<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Error in native code while writing vertical output: {}", e);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn corpus_new(
+    root: *const c_char,
+    exts: *const *const c_char,
+    n: usize,
+) -> *mut Corpus {
+    let root = unsafe {
+        assert!(!root.is_null());
+        CStr::from_ptr(root)
+    };
+    let root = root.to_str().unwrap();
+    let exts = unsafe { cstr_array_to_vec(exts, n) };
+    match Corpus::new(root, &exts) {
+        Ok(corpus) => {
+            debug!("Allocating Corpus {:?}", root);
+            Box::into_raw(Box::new(corpus))
+        }
+        Err(e) => {
+            error!("Error in native code while walking {:?}: {}", root, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn corpus_free(ptr: *mut Corpus) {
+    if ptr.is_null() {
+        return;
+    }
+    debug!("Deallocating Corpus");
+    unsafe {
+        Box::from_raw(ptr);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn corpus_next_line(ptr: *mut Corpus) -> *const c_char {
+    let corpus = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    match corpus.next_line() {
+        Some(Ok(line)) => {
+            debug!("Allocating string {:?}", line);
+            CString::new(line).unwrap().into_raw()
+        }
+        Some(Err(e)) => {
+            error!(
+                "Error in native code while reading next Corpus line: {}",
+                e
+            );
+            ptr::null()
+        }
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn corpus_next_parsed(ptr: *mut Corpus) -> *mut Line {
+    let corpus = unsafe {
+        assert!(!ptr.is_null());
+        &mut *ptr
+    };
+    match corpus.next_parsed() {
+        Some(Ok(line)) => {
+            debug!("Allocating parsed Line {:?}", line);
+            Box::into_raw(Box::new(line))
+        }
+        Some(Err(e)) => {
+            error!(
+                "Error in native code while parsing next Corpus line: {}",
+                e
+            );
+            ptr::null_mut()
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn corpus_current_file(ptr: *const Corpus) -> *const c_char {
+    let corpus = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    match corpus.current_file() {
+        Some(path) => CString::new(path.to_string_lossy().as_ref())
+            .unwrap()
+            .into_raw(),
+        None => ptr::null(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::io::Cursor;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn from_reader_yields_lines_from_any_bufread() {
+        // Exercises the same reader-generalization path `vertical_from_stdin`
+        // relies on, without needing a real stdin handle in a test.
+        let mut vertical = Vertical::from_reader(Box::new(Cursor::new(b"foo\nbar\n".to_vec())));
+        assert_eq!(vertical.next_line().unwrap().unwrap(), "foo");
+        assert_eq!(vertical.next_line().unwrap().unwrap(), "bar");
+        assert!(vertical.next_line().is_none());
+    }
+
+    fn read_all_lines(reader: Box<dyn BufRead>) -> Vec<String> {
+        let mut vertical = Vertical::from_reader(reader);
+        let mut lines = Vec::new();
+        while let Some(line) = vertical.next_line() {
+            lines.push(line.unwrap());
+        }
+        lines
+    }
+
+    #[test]
+    fn decompresses_gzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"foo\nbar\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let reader = decompressed(Cursor::new(compressed)).unwrap();
+        assert_eq!(read_all_lines(reader), vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn decompresses_zstd() {
+        let compressed = zstd::stream::encode_all(Cursor::new(b"foo\nbar\n".to_vec()), 0).unwrap();
+
+        let reader = decompressed(Cursor::new(compressed)).unwrap();
+        assert_eq!(read_all_lines(reader), vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn decompresses_bzip2() {
+        use bzip2::write::BzEncoder;
+        use bzip2::Compression;
+
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"foo\nbar\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let reader = decompressed(Cursor::new(compressed)).unwrap();
+        assert_eq!(read_all_lines(reader), vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn passes_through_plain_text_unchanged() {
+        let reader = decompressed(Cursor::new(b"foo\nbar\n".to_vec())).unwrap();
+        assert_eq!(read_all_lines(reader), vec!["foo", "bar"]);
+    }
+
+    fn batch_to_strings(batch: &LineBatch) -> Vec<String> {
+        (0..batch.len())
+            .map(|i| batch.get(i).unwrap().to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn next_batch_returns_full_batches_then_a_short_one_at_eof() {
+        let mut vertical =
+            Vertical::from_reader(Box::new(Cursor::new(b"a\nb\nc\nd\ne\n".to_vec())));
+
+        let first = vertical.next_batch(2).unwrap();
+        assert_eq!(batch_to_strings(&first), vec!["a", "b"]);
+
+        let second = vertical.next_batch(2).unwrap();
+        assert_eq!(batch_to_strings(&second), vec!["c", "d"]);
+
+        // Fewer lines remain than requested: a short, not empty, batch.
+        let third = vertical.next_batch(2).unwrap();
+        assert_eq!(batch_to_strings(&third), vec!["e"]);
+
+        let fourth = vertical.next_batch(2).unwrap();
+        assert_eq!(fourth.len(), 0);
+    }
+
+    #[test]
+    fn next_batch_does_not_trust_n_as_an_allocation_size() {
+        // An oversized `n` must not be used to pre-allocate: it should just
+        // return the short batch that's actually available, not abort.
+        let mut vertical = Vertical::from_reader(Box::new(Cursor::new(b"a\nb\n".to_vec())));
+        let batch = vertical.next_batch(1 << 40).unwrap();
+        assert_eq!(batch_to_strings(&batch), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn next_batch_keeps_lines_read_before_a_mid_batch_error() {
+        // A reader that yields two valid lines and then an I/O error.
+        struct FlakyReader {
+            remaining: std::collections::VecDeque<u8>,
+            failed: bool,
+        }
+        impl Read for FlakyReader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.remaining.is_empty() && !self.failed {
+                    self.failed = true;
+                    return Err(io::Error::new(io::ErrorKind::Other, "boom"));
+                }
+                let mut n = 0;
+                while n < buf.len() {
+                    match self.remaining.pop_front() {
+                        Some(b) => {
+                            buf[n] = b;
+                            n += 1;
+                        }
+                        None => break,
+                    }
+                }
+                Ok(n)
+            }
+        }
+        impl BufRead for FlakyReader {
+            fn fill_buf(&mut self) -> io::Result<&[u8]> {
+                if self.remaining.is_empty() && !self.failed {
+                    self.failed = true;
+                    return Err(io::Error::new(io::ErrorKind::Other, "boom"));
+                }
+                let (front, _) = self.remaining.as_slices();
+                Ok(front)
+            }
+            fn consume(&mut self, amt: usize) {
+                self.remaining.drain(..amt);
+            }
+        }
+
+        let mut vertical = Vertical::from_reader(Box::new(FlakyReader {
+            remaining: b"a\nb\n".iter().copied().collect(),
+            failed: false,
+        }));
+
+        // The two good lines come back even though the read after them fails.
+        let batch = vertical.next_batch(5).unwrap();
+        assert_eq!(batch_to_strings(&batch), vec!["a", "b"]);
+
+        // The error is surfaced on the next call rather than being lost.
+        assert!(vertical.next_batch(5).is_err());
+    }
 }
@@ -0,0 +1,226 @@
+//! Parsing of individual vertical lines into structured tokens and
+//! structural tags, as opposed to the raw `String` lines `Vertical`
+//! yields by default.
+
+use crate::CorpyResult;
+
+/// A single parsed line of a vertical: either a token (a tab-separated
+/// row of positional attributes) or one of the structural tag variants
+/// (`<doc ...>`, `</s>`, `<g/>`).
+#[derive(Debug, PartialEq, Clone)]
+pub enum Line {
+    Token(Vec<String>),
+    StructOpen {
+        name: String,
+        attrs: Vec<(String, String)>,
+    },
+    StructClose {
+        name: String,
+    },
+    StructEmpty {
+        name: String,
+        attrs: Vec<(String, String)>,
+    },
+}
+
+impl Line {
+    /// Numeric tag for the FFI boundary: 0 = `Token`, 1 = `StructOpen`,
+    /// 2 = `StructClose`, 3 = `StructEmpty`.
+    pub fn kind(&self) -> u8 {
+        match self {
+            Line::Token(_) => 0,
+            Line::StructOpen { .. } => 1,
+            Line::StructClose { .. } => 2,
+            Line::StructEmpty { .. } => 3,
+        }
+    }
+
+    pub fn column_count(&self) -> usize {
+        match self {
+            Line::Token(cols) => cols.len(),
+            _ => 0,
+        }
+    }
+
+    pub fn column(&self, i: usize) -> Option<&str> {
+        match self {
+            Line::Token(cols) => cols.get(i).map(String::as_str),
+            _ => None,
+        }
+    }
+
+    pub fn struct_name(&self) -> Option<&str> {
+        match self {
+            Line::StructOpen { name, .. }
+            | Line::StructClose { name }
+            | Line::StructEmpty { name, .. } => Some(name.as_str()),
+            Line::Token(_) => None,
+        }
+    }
+
+    pub fn attrs(&self) -> &[(String, String)] {
+        match self {
+            Line::StructOpen { attrs, .. } | Line::StructEmpty { attrs, .. } => attrs,
+            Line::StructClose { .. } | Line::Token(_) => &[],
+        }
+    }
+}
+
+/// Parses one raw vertical line into a [`Line`]. A line starting with `<`
+/// is treated as a structural tag (open, close or self-closing); anything
+/// else is tokenized on tabs.
+pub fn parse_line(raw: &str) -> CorpyResult<Line> {
+    let trimmed = raw.trim_end();
+    if trimmed.starts_with('<') {
+        parse_struct(trimmed)
+    } else {
+        Ok(Line::Token(trimmed.split('\t').map(String::from).collect()))
+    }
+}
+
+fn parse_struct(tag: &str) -> CorpyResult<Line> {
+    if !tag.ends_with('>') {
+        return Err(format!("Malformed structural tag: {:?}", tag));
+    }
+    let inner = &tag[1..tag.len() - 1];
+
+    if let Some(name) = inner.strip_prefix('/') {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(format!("Malformed structural tag: {:?}", tag));
+        }
+        return Ok(Line::StructClose { name });
+    }
+
+    let (inner, empty) = match inner.strip_suffix('/') {
+        Some(inner) => (inner.trim_end(), true),
+        None => (inner, false),
+    };
+
+    let mut parts = inner.splitn(2, char::is_whitespace);
+    let name = parts
+        .next()
+        .ok_or_else(|| format!("Malformed structural tag: {:?}", tag))?
+        .to_string();
+    if name.is_empty() {
+        return Err(format!("Malformed structural tag: {:?}", tag));
+    }
+    let attrs = match parts.next() {
+        Some(rest) => parse_attrs(rest.trim())?,
+        None => Vec::new(),
+    };
+
+    if empty {
+        Ok(Line::StructEmpty { name, attrs })
+    } else {
+        Ok(Line::StructOpen { name, attrs })
+    }
+}
+
+/// Parses `key="value"` pairs separated by whitespace, in the style of
+/// CWB/vertical structural attributes.
+fn parse_attrs(rest: &str) -> CorpyResult<Vec<(String, String)>> {
+    let mut attrs = Vec::new();
+    let mut chars = rest.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let key_end = rest[start..]
+            .find('=')
+            .map(|i| start + i)
+            .ok_or_else(|| format!("Malformed attribute in tag: {:?}", rest))?;
+        let key = rest[start..key_end].trim().to_string();
+
+        let mut value_start = key_end + 1;
+        if rest[value_start..].starts_with('"') {
+            value_start += 1;
+        } else {
+            return Err(format!("Malformed attribute in tag: {:?}", rest));
+        }
+        let value_end = rest[value_start..]
+            .find('"')
+            .map(|i| value_start + i)
+            .ok_or_else(|| format!("Unterminated attribute value in tag: {:?}", rest))?;
+        let value = decode_attr_value(&rest[value_start..value_end]);
+        attrs.push((key, value));
+
+        while let Some(&(i, _)) = chars.peek() {
+            chars.next();
+            if i >= value_end {
+                break;
+            }
+        }
+    }
+    Ok(attrs)
+}
+
+/// Inverts the escaping `writer::escape_attr` applies, so that
+/// round-tripping a struct tag through write then read yields the
+/// original attribute value back.
+fn decode_attr_value(value: &str) -> String {
+    value.replace("&quot;", "\"").replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_token() {
+        assert_eq!(
+            parse_line("foo\tbar\tbaz").unwrap(),
+            Line::Token(vec!["foo".into(), "bar".into(), "baz".into()])
+        );
+    }
+
+    #[test]
+    fn parses_struct_open_with_attrs() {
+        assert_eq!(
+            parse_line("<doc id=\"1\" title=\"A B\">").unwrap(),
+            Line::StructOpen {
+                name: "doc".into(),
+                attrs: vec![("id".into(), "1".into()), ("title".into(), "A B".into())],
+            }
+        );
+    }
+
+    #[test]
+    fn parses_struct_close() {
+        assert_eq!(
+            parse_line("</s>").unwrap(),
+            Line::StructClose { name: "s".into() }
+        );
+    }
+
+    #[test]
+    fn parses_struct_empty() {
+        assert_eq!(
+            parse_line("<g/>").unwrap(),
+            Line::StructEmpty {
+                name: "g".into(),
+                attrs: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_entities_escaped_on_write() {
+        assert_eq!(
+            parse_line("<doc title=\"A &quot;quoted&quot; &amp; more\">").unwrap(),
+            Line::StructOpen {
+                name: "doc".into(),
+                attrs: vec![("title".into(), "A \"quoted\" & more".into())],
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_tags_with_no_name() {
+        assert!(parse_line("<>").is_err());
+        assert!(parse_line("< >").is_err());
+        assert!(parse_line("</>").is_err());
+        assert!(parse_line("< />").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Symmetric counterpart to `Vertical`: writes tokens and structural tags
+//! out in vertical format instead of reading them.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::CorpyResult;
+
+pub struct VerticalWriter {
+    writer: Box<dyn Write>,
+}
+
+impl VerticalWriter {
+    pub fn new(path: &str) -> CorpyResult<Self> {
+        match File::create(path) {
+            Ok(file) => Ok(VerticalWriter::from_writer(Box::new(BufWriter::new(file)))),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        VerticalWriter::from_writer(Box::new(io::stdout().lock()))
+    }
+
+    pub fn from_writer(writer: Box<dyn Write>) -> Self {
+        VerticalWriter { writer }
+    }
+
+    pub fn write_token(&mut self, columns: &[&str]) -> CorpyResult<()> {
+        writeln!(self.writer, "{}", columns.join("\t")).map_err(|e| e.to_string())
+    }
+
+    pub fn write_struct_open(&mut self, name: &str, attrs: &[(&str, &str)]) -> CorpyResult<()> {
+        writeln!(self.writer, "<{}{}>", name, format_attrs(attrs)).map_err(|e| e.to_string())
+    }
+
+    pub fn write_struct_close(&mut self, name: &str) -> CorpyResult<()> {
+        writeln!(self.writer, "</{}>", name).map_err(|e| e.to_string())
+    }
+
+    pub fn write_struct_empty(&mut self, name: &str, attrs: &[(&str, &str)]) -> CorpyResult<()> {
+        writeln!(self.writer, "<{}{}/>", name, format_attrs(attrs)).map_err(|e| e.to_string())
+    }
+
+    pub fn flush(&mut self) -> CorpyResult<()> {
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+}
+
+fn format_attrs(attrs: &[(&str, &str)]) -> String {
+    attrs
+        .iter()
+        .map(|(key, value)| format!(" {}=\"{}\"", key, escape_attr(value)))
+        .collect()
+}
+
+fn escape_attr(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// An owned, `'static` `Write` sink that keeps a handle to the bytes
+    /// written so tests can inspect them after dropping the writer.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn writes_token_and_struct_tags() {
+        let buf = SharedBuf::default();
+        let mut writer = VerticalWriter::from_writer(Box::new(buf.clone()));
+        writer.write_struct_open("doc", &[("id", "1")]).unwrap();
+        writer.write_token(&["foo", "k1", "k1:foo"]).unwrap();
+        writer.write_struct_empty("g", &[]).unwrap();
+        writer.write_struct_close("doc").unwrap();
+        assert_eq!(
+            buf.contents(),
+            "<doc id=\"1\">\nfoo\tk1\tk1:foo\n<g/>\n</doc>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_quotes_and_ampersands_in_attrs() {
+        let buf = SharedBuf::default();
+        let mut writer = VerticalWriter::from_writer(Box::new(buf.clone()));
+        writer
+            .write_struct_open("doc", &[("title", "A \"quoted\" & more")])
+            .unwrap();
+        assert_eq!(
+            buf.contents(),
+            "<doc title=\"A &quot;quoted&quot; &amp; more\">\n"
+        );
+    }
+}